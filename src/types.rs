@@ -1,15 +1,20 @@
 use libc::{c_int, c_double};
 use std::c_str::{CString};
 use std::mem;
+use std::raw::Slice as RawSlice;
+use std::str;
 use std::vec;
 use super::ffi;
+use super::{SqliteError, SqliteResult};
 
 pub trait ToSql {
     unsafe fn bind_parameter(&self, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> c_int;
 }
 
+/// A trait for types that can be created from a SQLite column value; fallible so callers can
+/// tell a genuine `NULL` from a stored type that can't be coerced into `Self`.
 pub trait FromSql {
-    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> Self;
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<Self>;
 }
 
 macro_rules! raw_to_impl(
@@ -71,58 +76,252 @@ impl ToSql for Null {
     }
 }
 
+impl ToSql for bool {
+    unsafe fn bind_parameter(&self, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> c_int {
+        (*self as i64).bind_parameter(stmt, col)
+    }
+}
+
+impl ToSql for f32 {
+    unsafe fn bind_parameter(&self, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> c_int {
+        (*self as c_double).bind_parameter(stmt, col)
+    }
+}
+
+macro_rules! narrow_to_impl(
+    ($t:ty) => (
+        impl ToSql for $t {
+            unsafe fn bind_parameter(&self, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> c_int {
+                (*self as i64).bind_parameter(stmt, col)
+            }
+        }
+    )
+)
+
+narrow_to_impl!(i8)
+narrow_to_impl!(u8)
+narrow_to_impl!(i16)
+narrow_to_impl!(u16)
+narrow_to_impl!(u32)
+
+impl ToSql for u64 {
+    unsafe fn bind_parameter(&self, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> c_int {
+        // Unlike the other narrow_to_impl! types, `u64` doesn't always fit in the `i64` that
+        // SQLite stores integers as. Casting a too-large value would silently wrap to a
+        // negative number, so report it through the same bind-status channel the ffi calls
+        // below already use, rather than binding a corrupted value or panicking on valid input.
+        if *self > ::std::i64::MAX as u64 {
+            ffi::SQLITE_RANGE
+        } else {
+            (*self as i64).bind_parameter(stmt, col)
+        }
+    }
+}
+
 macro_rules! raw_from_impl(
-    ($t:ty, $f:ident) => (
+    ($t:ty, $f:ident, $($ok:ident)|+) => (
+        impl FromSql for $t {
+            unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<$t> {
+                match ffi::sqlite3_column_type(stmt, col) {
+                    $(ffi::$ok)|+ => Ok(ffi::$f(stmt, col)),
+                    _ => Err(SqliteError::InvalidColumnType),
+                }
+            }
+        }
+    )
+)
+
+raw_from_impl!(c_int, sqlite3_column_int, SQLITE_INTEGER)
+raw_from_impl!(i64, sqlite3_column_int64, SQLITE_INTEGER)
+raw_from_impl!(c_double, sqlite3_column_double, SQLITE_INTEGER | SQLITE_FLOAT)
+
+impl FromSql for bool {
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<bool> {
+        let value: i64 = try!(FromSql::column_result(stmt, col));
+        Ok(value != 0)
+    }
+}
+
+impl FromSql for f32 {
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<f32> {
+        let value: c_double = try!(FromSql::column_result(stmt, col));
+        Ok(value as f32)
+    }
+}
+
+// Narrower and unsigned integer types are read via the same `sqlite3_column_int64` as `i64`
+// (SQLite only has one integer storage class) and then range-checked against the target
+// type's bounds, so a value that doesn't fit is reported rather than silently truncated.
+macro_rules! narrow_from_impl(
+    ($t:ty, $min:expr, $max:expr) => (
         impl FromSql for $t {
-            unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> $t {
-                ffi::$f(stmt, col)
+            unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<$t> {
+                let value: i64 = try!(FromSql::column_result(stmt, col));
+                if value < $min || value > $max {
+                    Err(SqliteError::IntegralValueOutOfRange(value))
+                } else {
+                    Ok(value as $t)
+                }
             }
         }
     )
 )
 
-raw_from_impl!(c_int, sqlite3_column_int)
-raw_from_impl!(i64, sqlite3_column_int64)
-raw_from_impl!(c_double, sqlite3_column_double)
+narrow_from_impl!(i8, ::std::i8::MIN as i64, ::std::i8::MAX as i64)
+narrow_from_impl!(u8, 0, ::std::u8::MAX as i64)
+narrow_from_impl!(i16, ::std::i16::MIN as i64, ::std::i16::MAX as i64)
+narrow_from_impl!(u16, 0, ::std::u16::MAX as i64)
+narrow_from_impl!(u32, 0, ::std::u32::MAX as i64)
+narrow_from_impl!(u64, 0, ::std::i64::MAX)
 
 impl FromSql for String {
-    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> String {
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<String> {
+        if ffi::sqlite3_column_type(stmt, col) == ffi::SQLITE_NULL {
+            return Err(SqliteError::InvalidColumnType);
+        }
+
         let c_text = ffi::sqlite3_column_text(stmt, col);
         if c_text.is_null() {
-            "".to_string()
+            Ok("".to_string())
         } else {
             match CString::new(mem::transmute(c_text), false).as_str() {
-                Some(s) => s.to_string(),
-                None => "".to_string(),
+                Some(s) => Ok(s.to_string()),
+                None => Err(SqliteError::InvalidColumnType),
             }
         }
     }
 }
 
 impl FromSql for Vec<u8> {
-    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> Vec<u8> {
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<Vec<u8>> {
+        if ffi::sqlite3_column_type(stmt, col) == ffi::SQLITE_NULL {
+            return Err(SqliteError::InvalidColumnType);
+        }
+
         let c_blob = ffi::sqlite3_column_blob(stmt, col);
         let len = ffi::sqlite3_column_bytes(stmt, col);
 
         assert!(len >= 0); let len = len as uint;
 
-        vec::raw::from_buf(mem::transmute(c_blob), len)
+        Ok(vec::raw::from_buf(mem::transmute(c_blob), len))
+    }
+}
+
+/// A borrowed view of a column value; see `column_value_ref`.
+pub enum ValueRef<'stmt> {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(&'stmt str),
+    Blob(&'stmt [u8]),
+}
+
+/// Reads the column at `col` into a `ValueRef` without copying. `FromSql` can't express this
+/// safely (a blanket `impl<'a> FromSql for &'a str` would let safe code pick a lifetime that
+/// outlives the row), so callers must choose `'stmt` not to outlive the point where `stmt` is
+/// next stepped or the column re-read as another type.
+pub unsafe fn column_value_ref<'stmt>(stmt: *mut ffi::sqlite3_stmt, col: c_int)
+                                       -> SqliteResult<ValueRef<'stmt>> {
+    match ffi::sqlite3_column_type(stmt, col) {
+        ffi::SQLITE_NULL => Ok(ValueRef::Null),
+        ffi::SQLITE_INTEGER => Ok(ValueRef::Integer(ffi::sqlite3_column_int64(stmt, col))),
+        ffi::SQLITE_FLOAT => Ok(ValueRef::Real(ffi::sqlite3_column_double(stmt, col))),
+        ffi::SQLITE_TEXT => {
+            let c_text = ffi::sqlite3_column_text(stmt, col);
+            let len = ffi::sqlite3_column_bytes(stmt, col);
+            assert!(len >= 0); let len = len as uint;
+
+            let bytes: &'stmt [u8] = mem::transmute(RawSlice { data: c_text as *const u8, len: len });
+            match str::from_utf8(bytes) {
+                Some(s) => Ok(ValueRef::Text(s)),
+                None => Err(SqliteError::InvalidColumnType),
+            }
+        }
+        ffi::SQLITE_BLOB => {
+            let c_blob = ffi::sqlite3_column_blob(stmt, col);
+            let len = ffi::sqlite3_column_bytes(stmt, col);
+            assert!(len >= 0); let len = len as uint;
+
+            Ok(ValueRef::Blob(mem::transmute(RawSlice { data: c_blob as *const u8, len: len })))
+        }
+        _ => Err(SqliteError::InvalidColumnType),
     }
 }
 
 impl<T: FromSql> FromSql for Option<T> {
-    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> Option<T> {
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<Option<T>> {
         if ffi::sqlite3_column_type(stmt, col) == ffi::SQLITE_NULL {
-            None
+            Ok(None)
         } else {
-            Some(FromSql::column_result(stmt, col))
+            FromSql::column_result(stmt, col).map(Some)
+        }
+    }
+}
+
+/// A dynamically-typed, owned column value, for when a column's type isn't known until the
+/// query runs.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl FromSql for Value {
+    unsafe fn column_result(stmt: *mut ffi::sqlite3_stmt, col: c_int) -> SqliteResult<Value> {
+        match ffi::sqlite3_column_type(stmt, col) {
+            ffi::SQLITE_NULL => Ok(Value::Null),
+            ffi::SQLITE_INTEGER => FromSql::column_result(stmt, col).map(Value::Integer),
+            ffi::SQLITE_FLOAT => FromSql::column_result(stmt, col).map(Value::Real),
+            ffi::SQLITE_TEXT => FromSql::column_result(stmt, col).map(Value::Text),
+            ffi::SQLITE_BLOB => FromSql::column_result(stmt, col).map(Value::Blob),
+            _ => Err(SqliteError::InvalidColumnType),
+        }
+    }
+}
+
+impl ToSql for Value {
+    unsafe fn bind_parameter(&self, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> c_int {
+        match *self {
+            Value::Null => ffi::sqlite3_bind_null(stmt, col),
+            Value::Integer(i) => i.bind_parameter(stmt, col),
+            Value::Real(f) => f.bind_parameter(stmt, col),
+            Value::Text(ref s) => s.bind_parameter(stmt, col),
+            Value::Blob(ref b) => b.bind_parameter(stmt, col),
         }
     }
 }
 
+/// Resolves `name` (e.g. `:name`, `@name`, `$name`) to its parameter index via
+/// `sqlite3_bind_parameter_index` and binds `value` through `ToSql::bind_parameter`.
+pub unsafe fn bind_parameter_by_name(stmt: *mut ffi::sqlite3_stmt, name: &str, value: &ToSql)
+                                      -> SqliteResult<c_int> {
+    let idx = name.with_c_str(|c_name| ffi::sqlite3_bind_parameter_index(stmt, c_name));
+    if idx == 0 {
+        Err(SqliteError::InvalidParameterName(name.to_string()))
+    } else {
+        Ok(value.bind_parameter(stmt, idx))
+    }
+}
+
+/// Binds a whole set of named parameters at once via `bind_parameter_by_name`, so callers
+/// don't have to track each `c_int` result themselves.
+pub unsafe fn bind_named_params(stmt: *mut ffi::sqlite3_stmt, params: &[(&str, &ToSql)])
+                                 -> SqliteResult<()> {
+    for &(name, value) in params.iter() {
+        try!(bind_parameter_by_name(stmt, name, value));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
+    use std::ptr;
     use SqliteConnection;
+    use super::{bind_named_params, bind_parameter_by_name, column_value_ref};
+    use super::{ffi, FromSql, SqliteError, ToSql, Value, ValueRef};
 
     fn checked_memory_handle() -> SqliteConnection {
         let db = SqliteConnection::open(":memory:").unwrap();
@@ -177,4 +376,198 @@ mod test {
         assert!(s2.is_none());
         assert_eq!(b, b2);
     }
+
+    #[test]
+    fn test_invalid_column_type() {
+        let db = checked_memory_handle();
+
+        db.execute("INSERT INTO foo(t) VALUES ('hello')", []).unwrap();
+
+        let mut stmt = db.prepare("SELECT t FROM foo").unwrap();
+        let mut rows = stmt.query([]).unwrap();
+        let row = rows.next().unwrap().unwrap();
+
+        let result: Result<i64, SqliteError> = row.get_checked(0);
+        match result {
+            Err(SqliteError::InvalidColumnType) => (),
+            Ok(v) => panic!("expected InvalidColumnType, got Ok({})", v),
+            Err(e) => panic!("expected InvalidColumnType, got {}", e),
+        }
+    }
+
+    #[test]
+    fn test_value() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE numbers (i INTEGER, r REAL)").unwrap();
+
+        db.execute("INSERT INTO foo(t) VALUES ('hello')", []).unwrap();
+        db.execute("INSERT INTO foo(b) VALUES (?)", &[&vec![1u8,2,3,4]]).unwrap();
+        db.execute("INSERT INTO foo(b, t) VALUES (NULL, NULL)", []).unwrap();
+        db.execute("INSERT INTO numbers(i, r) VALUES (42, 3.5)", []).unwrap();
+
+        let mut stmt = db.prepare("SELECT t, b FROM foo ORDER BY ROWID ASC").unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let row1 = rows.next().unwrap().unwrap();
+        let v1: Value = row1.get(0);
+        assert_eq!(v1, Value::Text("hello".to_string()));
+
+        let row2 = rows.next().unwrap().unwrap();
+        let v2: Value = row2.get(1);
+        assert_eq!(v2, Value::Blob(vec![1u8,2,3,4]));
+
+        let row3 = rows.next().unwrap().unwrap();
+        let v3: Value = row3.get(0);
+        assert_eq!(v3, Value::Null);
+
+        let mut num_stmt = db.prepare("SELECT i, r FROM numbers").unwrap();
+        let mut num_rows = num_stmt.query([]).unwrap();
+        let num_row = num_rows.next().unwrap().unwrap();
+
+        let vi: Value = num_row.get(0);
+        assert_eq!(vi, Value::Integer(42));
+
+        let vr: Value = num_row.get(1);
+        assert_eq!(vr, Value::Real(3.5));
+    }
+
+    #[test]
+    fn test_bool() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE bools (b BOOLEAN)").unwrap();
+
+        db.execute("INSERT INTO bools(b) VALUES (?)", &[&true]).unwrap();
+        db.execute("INSERT INTO bools(b) VALUES (?)", &[&false]).unwrap();
+
+        let mut stmt = db.prepare("SELECT b FROM bools ORDER BY ROWID ASC").unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let b1: bool = rows.next().unwrap().unwrap().get(0);
+        assert!(b1);
+
+        let b2: bool = rows.next().unwrap().unwrap().get(0);
+        assert!(!b2);
+    }
+
+    #[test]
+    fn test_narrow_int_overflow() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE ints (i INTEGER)").unwrap();
+        db.execute("INSERT INTO ints(i) VALUES (300)", []).unwrap();
+
+        let mut stmt = db.prepare("SELECT i FROM ints").unwrap();
+        let mut rows = stmt.query([]).unwrap();
+        let row = rows.next().unwrap().unwrap();
+
+        let result: Result<u8, SqliteError> = row.get_checked(0);
+        match result {
+            Err(SqliteError::IntegralValueOutOfRange(300)) => (),
+            Ok(v) => panic!("expected IntegralValueOutOfRange, got Ok({})", v),
+            Err(e) => panic!("expected IntegralValueOutOfRange(300), got {}", e),
+        }
+    }
+
+    #[test]
+    fn test_u64() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE ints (i INTEGER)").unwrap();
+
+        let in_range = 42u64;
+        db.execute("INSERT INTO ints(i) VALUES (?)", &[&in_range]).unwrap();
+
+        let v: i64 = db.query_row("SELECT i FROM ints", [], |r| r.unwrap().get(0));
+        assert_eq!(v, 42);
+
+        let too_big = ::std::u64::MAX;
+        assert!(db.execute("INSERT INTO ints(i) VALUES (?)", &[&too_big]).is_err());
+    }
+
+    unsafe fn raw_open() -> *mut ffi::sqlite3 {
+        let mut db: *mut ffi::sqlite3 = ptr::null_mut();
+        assert_eq!(":memory:".with_c_str(|c_path| ffi::sqlite3_open(c_path, &mut db)), ffi::SQLITE_OK);
+        db
+    }
+
+    unsafe fn raw_prepare(db: *mut ffi::sqlite3, sql: &str) -> *mut ffi::sqlite3_stmt {
+        let mut stmt: *mut ffi::sqlite3_stmt = ptr::null_mut();
+        assert_eq!(
+            sql.with_c_str(|c_sql| ffi::sqlite3_prepare_v2(db, c_sql, -1, &mut stmt, ptr::null_mut())),
+            ffi::SQLITE_OK);
+        stmt
+    }
+
+    #[test]
+    fn test_bind_named_params() {
+        unsafe {
+            let db = raw_open();
+
+            let create = raw_prepare(db, "CREATE TABLE foo (a INTEGER, b TEXT)");
+            assert_eq!(ffi::sqlite3_step(create), ffi::SQLITE_DONE);
+            ffi::sqlite3_finalize(create);
+
+            let insert = raw_prepare(db, "INSERT INTO foo (a, b) VALUES (:a, :b)");
+            let a = 42i64;
+            let b = "hello".to_string();
+            bind_named_params(insert, &[(":a", &a as &ToSql), (":b", &b as &ToSql)]).unwrap();
+            assert_eq!(ffi::sqlite3_step(insert), ffi::SQLITE_DONE);
+            ffi::sqlite3_finalize(insert);
+
+            let select = raw_prepare(db, "SELECT a, b FROM foo");
+            assert_eq!(ffi::sqlite3_step(select), ffi::SQLITE_ROW);
+            let got_a: i64 = FromSql::column_result(select, 0).unwrap();
+            let got_b: String = FromSql::column_result(select, 1).unwrap();
+            assert_eq!(got_a, 42);
+            assert_eq!(got_b, "hello".to_string());
+            ffi::sqlite3_finalize(select);
+
+            ffi::sqlite3_close(db);
+        }
+    }
+
+    #[test]
+    fn test_bind_parameter_by_name_unknown() {
+        unsafe {
+            let db = raw_open();
+            let stmt = raw_prepare(db, "SELECT :a");
+
+            let value = 1i64;
+            match bind_parameter_by_name(stmt, ":nonexistent", &value as &ToSql) {
+                Err(SqliteError::InvalidParameterName(ref name)) => {
+                    assert_eq!(name.as_slice(), ":nonexistent");
+                }
+                Ok(_) => panic!("expected InvalidParameterName, got Ok"),
+                Err(_) => panic!("expected InvalidParameterName"),
+            }
+
+            ffi::sqlite3_finalize(stmt);
+            ffi::sqlite3_close(db);
+        }
+    }
+
+    #[test]
+    fn test_column_value_ref() {
+        unsafe {
+            let db = raw_open();
+            let stmt = raw_prepare(db, "SELECT 'hello', X'01020304', NULL");
+            assert_eq!(ffi::sqlite3_step(stmt), ffi::SQLITE_ROW);
+
+            match column_value_ref(stmt, 0).unwrap() {
+                ValueRef::Text(s) => assert_eq!(s, "hello"),
+                _ => panic!("expected ValueRef::Text"),
+            }
+
+            match column_value_ref(stmt, 1).unwrap() {
+                ValueRef::Blob(b) => assert_eq!(b, vec![1u8,2,3,4].as_slice()),
+                _ => panic!("expected ValueRef::Blob"),
+            }
+
+            match column_value_ref(stmt, 2).unwrap() {
+                ValueRef::Null => (),
+                _ => panic!("expected ValueRef::Null"),
+            }
+
+            ffi::sqlite3_finalize(stmt);
+            ffi::sqlite3_close(db);
+        }
+    }
 }
\ No newline at end of file